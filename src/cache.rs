@@ -0,0 +1,192 @@
+//! A disk-backed cache for generated image artifacts (previews, full images,
+//! the zip archive). Entries are always persisted to `root`; only a bounded
+//! amount of "hot" data is kept memory-mapped at once, so a directory of
+//! thousands of photos no longer has to fit entirely in RAM.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use memmap2::Mmap;
+
+use crate::range::ranged_response;
+
+/// Metadata kept in memory for every cached entry, whether or not its bytes
+/// are currently memory-mapped.
+#[derive(Debug, Clone)]
+pub struct ImageMetadata {
+    pub content_type: &'static str,
+    pub last_access: Instant,
+}
+
+struct Entry {
+    metadata: ImageMetadata,
+    mapping: Option<Arc<Mmap>>,
+}
+
+/// A key -> (bytes, metadata) store, persisted under `root` on disk, with an
+/// in-memory index and a configurable byte-budget LRU eviction policy for the
+/// memory-mapped "hot" entries.
+pub struct ImageCache {
+    root: PathBuf,
+    resident_budget: u64,
+    resident_bytes: Mutex<u64>,
+    index: Mutex<HashMap<String, Entry>>,
+}
+
+impl ImageCache {
+    /// Opens (creating if needed) a cache persisted under `root`, evicting
+    /// memory-mapped entries once their combined size would exceed
+    /// `resident_budget` bytes.
+    pub fn open(root: impl Into<PathBuf>, resident_budget: u64) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root,
+            resident_budget,
+            resident_bytes: Mutex::new(0),
+            index: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Resolves `key` to a path under `root`, rejecting anything that isn't a
+    /// single plain path component (no separators, no `..`, no absolute-path
+    /// prefix) so a cache key can never escape `root`.
+    fn path_for(&self, key: &str) -> io::Result<PathBuf> {
+        let is_plain_component = !key.is_empty()
+            && !key.contains('/')
+            && !key.contains('\\')
+            && !key.contains("..")
+            && !Path::new(key).is_absolute();
+
+        if !is_plain_component {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("refusing to use {key:?} as a cache key: not a plain file name"),
+            ));
+        }
+
+        Ok(self.root.join(key))
+    }
+
+    /// Persists `bytes` under `key` and registers it in the index. The entry
+    /// is not memory-mapped until the first [`ImageCache::get`].
+    pub fn insert(&self, key: &str, content_type: &'static str, bytes: &[u8]) -> io::Result<()> {
+        fs::write(self.path_for(key)?, bytes)?;
+
+        let previous = self.index.lock().unwrap().insert(
+            key.to_string(),
+            Entry {
+                metadata: ImageMetadata {
+                    content_type,
+                    last_access: Instant::now(),
+                },
+                mapping: None,
+            },
+        );
+
+        if let Some(mapping) = previous.and_then(|e| e.mapping) {
+            *self.resident_bytes.lock().unwrap() -= mapping.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether `key` is already cached, without touching its backing
+    /// bytes or bumping its `last_access`.
+    pub fn contains(&self, key: &str) -> bool {
+        self.index.lock().unwrap().contains_key(key)
+    }
+
+    /// Reads the bytes for `key`, memory-mapping the backing file on first
+    /// access. Bumps `last_access` and, if resident bytes now exceed the
+    /// configured budget, evicts the coldest other mappings (the underlying
+    /// files are untouched, so they're simply re-mapped on the next request).
+    ///
+    /// The index lock is only held for the lookups and the eviction pass — mapping
+    /// the file on a cold access and copying the bytes on every access happen
+    /// with the lock released, so concurrent requests for different keys
+    /// don't serialize behind one another's I/O or copy.
+    pub fn get(&self, key: &str) -> io::Result<Option<(ImageMetadata, Vec<u8>)>> {
+        let resident = {
+            let mut index = self.index.lock().unwrap();
+            let Some(entry) = index.get_mut(key) else {
+                return Ok(None);
+            };
+            entry.metadata.last_access = Instant::now();
+            entry.mapping.clone()
+        };
+
+        let mapping = match resident {
+            Some(mapping) => mapping,
+            None => {
+                let file = fs::File::open(self.path_for(key)?)?;
+                let mapping = Arc::new(unsafe { Mmap::map(&file)? });
+
+                let mut index = self.index.lock().unwrap();
+                let Some(entry) = index.get_mut(key) else {
+                    return Ok(None);
+                };
+                match &entry.mapping {
+                    Some(existing) => existing.clone(),
+                    None => {
+                        *self.resident_bytes.lock().unwrap() += mapping.len() as u64;
+                        entry.mapping = Some(mapping.clone());
+                        mapping
+                    }
+                }
+            }
+        };
+
+        let metadata = {
+            let mut index = self.index.lock().unwrap();
+            let Some(metadata) = index.get(key).map(|e| e.metadata.clone()) else {
+                return Ok(None);
+            };
+            self.evict_cold_entries(&mut index, key);
+            metadata
+        };
+
+        Ok(Some((metadata, mapping.to_vec())))
+    }
+
+    /// Looks up `key` and renders it as an HTTP response, honoring the
+    /// request's `Range` header. Responds `404 Not Found` for unknown keys.
+    pub fn respond(&self, key: &str, headers: &HeaderMap) -> Response {
+        match self.get(key) {
+            Ok(Some((metadata, bytes))) => ranged_response(bytes, metadata.content_type, headers),
+            Ok(None) => StatusCode::NOT_FOUND.into_response(),
+            Err(e) => {
+                log::error!(target: "image_cache", "Failed to read cache entry {key}: {e:?}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+
+    /// Drops memory mappings for the least-recently-used entries (other than
+    /// `keep`) until resident bytes fall back under budget.
+    fn evict_cold_entries(&self, index: &mut HashMap<String, Entry>, keep: &str) {
+        let mut resident = self.resident_bytes.lock().unwrap();
+
+        while *resident > self.resident_budget {
+            let coldest = index
+                .iter()
+                .filter(|(k, e)| k.as_str() != keep && e.mapping.is_some())
+                .min_by_key(|(_, e)| e.metadata.last_access)
+                .map(|(k, _)| k.clone());
+
+            let Some(coldest) = coldest else {
+                break;
+            };
+
+            if let Some(mapping) = index.get_mut(&coldest).and_then(|e| e.mapping.take()) {
+                *resident -= mapping.len() as u64;
+            }
+        }
+    }
+}
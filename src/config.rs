@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Runtime configuration for the gallery server, parsed from the command line.
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about = "Serves a directory of photos as a gallery")]
+pub struct AppConfig {
+    /// Directory to scan for `.jpg`/`.jpeg` files
+    #[arg(long, default_value = ".")]
+    pub dir: PathBuf,
+
+    /// Port to bind the HTTP server to
+    #[arg(long, default_value_t = 80)]
+    pub port: u16,
+
+    /// Width, in pixels, that previews are scaled down to
+    #[arg(long, default_value_t = 900)]
+    pub preview_width: u32,
+
+    /// Height, in pixels, that previews are scaled down to
+    #[arg(long, default_value_t = 600)]
+    pub preview_height: u32,
+
+    /// WebP encoding quality for previews, clamped to 1.0..=100.0
+    /// (the `webp` encoder panics on out-of-range values)
+    #[arg(long, default_value_t = 35.0, value_parser = parse_quality)]
+    pub quality: f32,
+
+    /// Explicit list of image paths to serve instead of globbing `--dir`
+    #[arg(long)]
+    pub file: Vec<PathBuf>,
+
+    /// Directory the on-disk image cache is persisted under
+    #[arg(long, default_value = ".cache")]
+    pub cache_dir: PathBuf,
+
+    /// Maximum number of bytes of memory-mapped cache entries kept resident
+    /// before the coldest ones are evicted
+    #[arg(long, default_value_t = 512 * 1024 * 1024)]
+    pub cache_budget: u64,
+
+    /// Number of images decoded concurrently at startup. Defaults to the
+    /// available parallelism
+    #[arg(long)]
+    pub workers: Option<usize>,
+
+    /// Maximum accepted size, in bytes, of a `POST /upload` request body
+    #[arg(long, default_value_t = 50 * 1024 * 1024)]
+    pub upload_body_limit: usize,
+}
+
+/// Parses [`AppConfig`] from the process's command-line arguments.
+pub fn cli_init() -> AppConfig {
+    AppConfig::parse()
+}
+
+/// Parses `--quality`, clamping it to the range the webp/jpeg encoders
+/// actually accept instead of panicking on an out-of-range value later.
+fn parse_quality(s: &str) -> Result<f32, String> {
+    let quality: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    Ok(quality.clamp(1.0, 100.0))
+}
@@ -0,0 +1,118 @@
+//! Shared, mutable gallery state. `axum::Router`s are immutable once served,
+//! so newly uploaded photos can't be registered as new routes after startup;
+//! instead the root-level asset routes read through this index on every
+//! request, and uploads simply append to it.
+
+use std::io::{Cursor, Write};
+use std::sync::{Arc, RwLock};
+
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::cache::ImageCache;
+use crate::palette::Palette;
+
+/// One served photo: its full-image cache key, preview cache key, and
+/// extracted palette.
+#[derive(Clone)]
+pub struct GalleryEntry {
+    pub fname: String,
+    pub preview_key: String,
+    pub palette: Palette,
+}
+
+/// The live set of served photos, backed by `cache`. Guarded by a single
+/// `RwLock` since uploads are rare compared to the reads every page load and
+/// asset request does.
+pub struct Gallery {
+    cache: Arc<ImageCache>,
+    entries: RwLock<Vec<GalleryEntry>>,
+}
+
+impl Gallery {
+    pub fn new(cache: Arc<ImageCache>) -> Self {
+        Self {
+            cache,
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn cache(&self) -> Arc<ImageCache> {
+        self.cache.clone()
+    }
+
+    /// Appends every entry from a single upload request and rebuilds the zip
+    /// archive once, rather than once per entry.
+    pub fn extend(&self, entries: impl IntoIterator<Item = GalleryEntry>) {
+        self.entries.write().unwrap().extend(entries);
+        self.rebuild_zip();
+    }
+
+    /// Bulk-registers `entries` (used once at startup) and rebuilds the zip
+    /// a single time, rather than once per entry.
+    pub fn seed(&self, entries: Vec<GalleryEntry>) {
+        *self.entries.write().unwrap() = entries;
+        self.rebuild_zip();
+    }
+
+    /// Whether `name` is a known full-image or preview cache key.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .any(|e| e.fname == name || e.preview_key == name)
+    }
+
+    /// Returns the JSON-serialized palette for `fname`, if known.
+    pub fn palette_json(&self, fname: &str) -> Option<Vec<u8>> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .find(|e| e.fname == fname)
+            .map(|e| crate::palette::to_json(&e.palette))
+    }
+
+    /// Renders the `<a>` tile list for the home page from the current
+    /// entries, using each photo's dominant palette color as a placeholder
+    /// background while its preview loads.
+    pub fn home_page_body(&self, preview_width: u32, preview_height: u32) -> String {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                let [r, g, b] = entry.palette.dominant;
+                format!(
+                    "<a href=\"{}\" style=\"background-color:#{r:02x}{g:02x}{b:02x};\"><img src=\"/{}\" style=\"width:{preview_width}px;height:{preview_height}px;\"></a><br>",
+                    entry.fname, entry.preview_key
+                )
+            })
+            .collect()
+    }
+
+    fn rebuild_zip(&self) {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for entry in self.entries.read().unwrap().iter() {
+            let Ok(Some((_, bytes))) = self.cache.get(&entry.fname) else {
+                continue;
+            };
+
+            if zip
+                .start_file(&entry.fname, FileOptions::default())
+                .is_err()
+            {
+                continue;
+            }
+            let _ = zip.write_all(&bytes);
+        }
+
+        if let Ok(cursor) = zip.finish() {
+            let _ = self
+                .cache
+                .insert("images.zip", "application/zip", &cursor.into_inner());
+        }
+    }
+}
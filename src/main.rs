@@ -1,33 +1,42 @@
-#![feature(string_leak)]
-#![feature(arc_into_inner)]
-#![feature(iterator_try_collect)]
-#![feature(path_file_prefix)]
+mod cache;
+mod config;
+mod gallery;
+mod palette;
+mod range;
+mod resize;
+mod upload;
 
-use std::fs::read_dir;
-use std::mem::{forget, transmute, MaybeUninit};
 use std::net::{Ipv4Addr, SocketAddrV4};
-use std::ops::Deref;
 use std::sync::mpsc::channel;
+use std::sync::Arc;
 use std::time::Instant;
 
-use axum::http::HeaderValue;
+use axum::extract::{DefaultBodyLimit, Multipart, Path, Query};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::IntoResponse;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::{Router, Server};
 use fern::Dispatch;
 use image::codecs::jpeg::JpegDecoder;
 use image::DynamicImage;
 use log::{error, LevelFilter};
-use rayon::spawn;
-use std::io::{Cursor, Read, Write};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::fs::read_dir;
+use std::io::Read;
 use tower_http::compression::CompressionLayer;
-use zip::write::FileOptions;
-use zip::ZipWriter;
+
+use cache::ImageCache;
+use config::{cli_init, AppConfig};
+use gallery::{Gallery, GalleryEntry};
+use resize::ResizeParams;
 
 #[tokio::main]
 async fn main() {
     let start_time = Instant::now();
 
+    let config = cli_init();
+
     Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
@@ -47,29 +56,53 @@ async fn main() {
         .apply()
         .unwrap();
 
-    let entries = read_dir(".")
-        .expect("Reading current directory")
-        .filter_map(|res| {
-            let entry = res.expect("Error listing entry");
-            match entry.path().extension().map(|x| x.to_str()).flatten() {
-                Some("jpg" | "jpeg") => Some(entry),
-                _ => None,
-            }
-        })
-        .collect::<Vec<_>>();
+    let entries = if config.file.is_empty() {
+        read_dir(&config.dir)
+            .expect("Reading scan directory")
+            .filter_map(|res| {
+                let entry = res.expect("Error listing entry");
+                match entry.path().extension().map(|x| x.to_str()).flatten() {
+                    Some("jpg" | "jpeg") => Some(entry.path()),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>()
+    } else {
+        config.file.clone()
+    };
 
     let entry_count = entries.len();
 
+    let cache = Arc::new(
+        ImageCache::open(&config.cache_dir, config.cache_budget).expect("Opening image cache"),
+    );
+
+    // Bounded worker pool: at most `workers` images are decoded and held in
+    // memory at once, instead of spawning one task per file up front
+    let workers = config.workers.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .expect("Building worker pool");
+
     let (image_sender, image_receiver) = channel();
 
-    entries
-        .into_iter()
-        .zip(vec![image_sender; entry_count])
-        .enumerate()
-        .for_each(|(i, (file, image_sender))| {
-            // Multithreaded image loading and preview generation
-            spawn(move || {
-                let path = file.path();
+    pool.install(|| {
+        entries
+            .into_par_iter()
+            .zip(vec![image_sender; entry_count])
+            .enumerate()
+            .for_each(|(i, (path, image_sender))| {
+                let fname = path.file_name().unwrap().to_str().unwrap().to_string();
+                let preview_key = format!(
+                    "preview_{}.webp",
+                    path.file_prefix().unwrap().to_str().unwrap()
+                );
 
                 // I read the whole image to memory, even though there is a method in image
                 // to do that. For some reason, this is around 10x faster
@@ -80,98 +113,174 @@ async fn main() {
 
                 let mut decoder =
                     JpegDecoder::new(full.as_slice()).expect("Initializing decoder for image");
-                decoder.scale(900, 600).expect("Scaling image");
+                decoder
+                    .scale(config.preview_width as u16, config.preview_height as u16)
+                    .expect("Scaling image");
                 let image = DynamicImage::from_decoder(decoder).expect("Decoding image");
 
+                let palette = palette::extract(&image);
+
                 let preview = webp::Encoder::from_image(&image)
                     .expect("Encoding to webp")
-                    .encode(35.0);
-
-                // Leak full as it will be used for the duration of the program,
-                // but will not be modified, so we don't need the extra data in Vec
-                let full: &[u8] = full.leak();
+                    .encode(config.quality);
 
-                // Leak the webp
-                // Since there isn't a leak method, we manually leak it
-                let preview = unsafe {
-                    let ptr: &[u8] = transmute(preview.deref());
-                    forget(preview);
-                    ptr
-                };
+                cache
+                    .insert(&fname, "image/jpeg", &full)
+                    .expect("Caching full image");
+                cache
+                    .insert(&preview_key, "image/webp", &preview)
+                    .expect("Caching preview");
 
-                let _ = image_sender.send((i, path, preview, full));
+                let _ = image_sender.send((i, path, preview_key, fname, palette));
             });
-        });
-
-    let mut home_page_lines = Vec::with_capacity(entry_count);
-
-    for _ in 0..entry_count {
-        home_page_lines.push(MaybeUninit::uninit());
-    }
-
-    let mut router = Router::new();
-    // In-memory zip of all images
-    let mut all_zip = ZipWriter::new(Cursor::new(Vec::new()));
-
-    while let Ok((i, path, preview_image, full_image)) = image_receiver.recv() {
-        let fname = path.file_name().unwrap().to_str().unwrap();
-        let preview_name = format!(
-            "/preview_{}.webp",
-            path.file_prefix().unwrap().to_str().unwrap()
-        );
-
-        // Register handlers for preview and full images
-        router = router
-            .route(
-                &(format!("/{fname}")),
-                get(move || async {
-                    let mut resp = full_image.into_response();
-                    resp.headers_mut()
-                        .insert("Content-Type", HeaderValue::from_static("image/jpeg"));
-                    resp
-                }),
-            )
-            .route(
-                &preview_name,
-                get(move || async {
-                    let mut resp = preview_image.into_response();
+    });
+
+    // Collect results in the original scan order, then seed the gallery once;
+    // uploads append to it afterwards without needing a new route per photo
+    let mut results = image_receiver.iter().collect::<Vec<_>>();
+    results.sort_by_key(|(i, ..)| *i);
+
+    let gallery = Arc::new(Gallery::new(cache.clone()));
+    gallery.seed(
+        results
+            .into_iter()
+            .map(|(_, _path, preview_key, fname, palette)| GalleryEntry {
+                fname,
+                preview_key,
+                palette,
+            })
+            .collect(),
+    );
+
+    println!("Image processing completed in {:?}", start_time.elapsed());
+
+    let router = Router::new()
+        .route(
+            "/",
+            // Serve the home page, rendered fresh from the gallery each request
+            get({
+                let gallery = gallery.clone();
+                let config = config.clone();
+                move || async move {
+                    let mut resp = home_page(&gallery, &config).into_response();
                     resp.headers_mut()
-                        .insert("Content-Type", HeaderValue::from_static("image/webp"));
+                        .insert("Content-Type", HeaderValue::from_static("text/html"));
                     resp
-                }),
-            );
-
-        // Add image to home page
-        home_page_lines
-            .get_mut(i)
-            .unwrap()
-            .write(
-                format!("<a href=\"{fname}\"><img src=\"{preview_name}\" style=\"width:900px;height:600px;\"></a><br>")
-            );
-
-        // Zip
-        all_zip
-            .start_file(fname, FileOptions::default())
-            .expect("image to zip");
-
-        all_zip.write_all(full_image).expect("image to zip");
-    }
-
-    let home_page_body = home_page_lines
-        .into_iter()
-        .map(|x| unsafe { MaybeUninit::assume_init(x) })
-        .collect::<String>();
-
-    // Finalize the zip and leak that data too
-    let all_zip: &[u8] = all_zip
-        .finish()
-        .expect("Zip to succeed")
-        .into_inner()
-        .leak();
+                }
+            }),
+        )
+        .route(
+            "/images.zip",
+            // Serve zip, reading through the cache
+            get({
+                let cache = cache.clone();
+                move |headers: HeaderMap| async move { cache.respond("images.zip", &headers) }
+            }),
+        )
+        .route(
+            "/resize/:fname",
+            // On-demand derivative sizes, cached on first request
+            get({
+                let gallery = gallery.clone();
+                let cache = cache.clone();
+                move |Path(fname): Path<String>,
+                      Query(params): Query<ResizeParams>,
+                      headers: HeaderMap| {
+                    let gallery = gallery.clone();
+                    let cache = cache.clone();
+                    async move { resize::serve(&gallery, &cache, &fname, params, &headers).await }
+                }
+            }),
+        )
+        .route(
+            "/palette/:name",
+            // Full palette for a photo, looked up by stripping the `.json` suffix
+            get({
+                let gallery = gallery.clone();
+                move |Path(name): Path<String>| async move {
+                    let Some(fname) = name.strip_suffix(".json") else {
+                        return StatusCode::NOT_FOUND.into_response();
+                    };
+                    match gallery.palette_json(fname) {
+                        Some(json) => {
+                            let mut resp = json.into_response();
+                            resp.headers_mut().insert(
+                                "Content-Type",
+                                HeaderValue::from_static("application/json"),
+                            );
+                            resp
+                        }
+                        None => StatusCode::NOT_FOUND.into_response(),
+                    }
+                }
+            }),
+        )
+        .route(
+            "/upload",
+            // Add photos to the running gallery without a restart
+            post({
+                let gallery = gallery.clone();
+                let preview_width = config.preview_width;
+                let preview_height = config.preview_height;
+                let quality = config.quality;
+                move |multipart: Multipart| {
+                    upload::upload(
+                        gallery.clone(),
+                        preview_width,
+                        preview_height,
+                        quality,
+                        multipart,
+                    )
+                }
+            })
+            .layer(DefaultBodyLimit::max(config.upload_body_limit)),
+        )
+        .route(
+            "/:name",
+            // Full images and previews, looked up dynamically since the
+            // gallery can grow after the router has started serving
+            get({
+                let gallery = gallery.clone();
+                let cache = cache.clone();
+                move |Path(name): Path<String>, headers: HeaderMap| async move {
+                    if gallery.contains(&name) {
+                        cache.respond(&name, &headers)
+                    } else {
+                        StatusCode::NOT_FOUND.into_response()
+                    }
+                }
+            }),
+        )
+        .layer(CompressionLayer::new());
 
-    println!("Image processing completed in {:?}", start_time.elapsed());
+    // Allow ctrl-c to be gracefully handled
+    let fut = async {
+        println!("Deployed to all interfaces!");
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(target: "console_server", "Faced the following error while listening for ctrl_c: {e:?}");
+            return;
+        }
+        println!("Ending...");
+    };
 
-    let home_page_doc = format!(
+    let port = config.port;
+    println!("Deploying to {port}");
+
+    Server::bind(&std::net::SocketAddr::V4(SocketAddrV4::new(
+        Ipv4Addr::new(0, 0, 0, 0),
+        port,
+    )))
+    .serve(router.into_make_service())
+    .with_graceful_shutdown(fut)
+    .await
+    .expect("Running server");
+}
+
+/// Renders the full home page document from the gallery's current entries.
+fn home_page(gallery: &Gallery, config: &AppConfig) -> String {
+    let home_page_body = gallery.home_page_body(config.preview_width, config.preview_height);
+
+    format!(
         "<html>
 <head>
     <link href =\"https://fonts.googleapis.com\" rel=\"preconnect\">
@@ -185,17 +294,17 @@ async fn main() {
         body {{
             background-color: #0c0c0c;
         }}
-        
+
         p {{
             line-height: 35px;
             max-width: 800px;
             margin: auto;
         }}
-        
+
         h1 {{
             font-size: 60px;
         }}
-        
+
         h2 {{
             font-size: 40px;
         }}
@@ -210,53 +319,5 @@ async fn main() {
 {home_page_body}
     </body>
 </html>"
-    );
-
-    let home_page_doc: &str = home_page_doc.leak();
-
-    let router = router
-        .route(
-            "/",
-            // Serve home page
-            get(move || async {
-                let mut resp = home_page_doc.into_response();
-                resp.headers_mut()
-                    .insert("Content-Type", HeaderValue::from_static("text/html"));
-                resp
-            }),
-        )
-        .route(
-            "/images.zip",
-            // Serve zip
-            get(move || async {
-                let mut resp = all_zip.into_response();
-                resp.headers_mut()
-                    .insert("Content-Type", HeaderValue::from_static("application/zip"));
-                resp
-            }),
-        )
-        .layer(CompressionLayer::new());
-
-    // Allow ctrl-c to be gracefully handled
-    let fut = async {
-        println!("Deployed to all interfaces!");
-        if let Err(e) = tokio::signal::ctrl_c().await {
-            error!(target: "console_server", "Faced the following error while listening for ctrl_c: {e:?}");
-            return;
-        }
-        println!("Ending...");
-    };
-
-    // Get port from command line or default to 80
-    let port = std::env::args().last().unwrap().parse().unwrap_or(80);
-    println!("Deploying to {port}");
-
-    Server::bind(&std::net::SocketAddr::V4(SocketAddrV4::new(
-        Ipv4Addr::new(0, 0, 0, 0),
-        port,
-    )))
-    .serve(router.into_make_service())
-    .with_graceful_shutdown(fut)
-    .await
-    .expect("Running server");
+    )
 }
@@ -0,0 +1,71 @@
+//! Dominant-color palette extraction using NeuQuant neural-net quantization,
+//! so a gallery tile can show a representative background color while its
+//! preview is still loading.
+
+use color_quant::NeuQuant;
+use image::DynamicImage;
+use serde::Serialize;
+
+/// Sampling factor passed to NeuQuant: every Nth pixel is used to train the
+/// network, trading palette accuracy for speed.
+const SAMPLE_FACTOR: i32 = 10;
+/// Target palette size.
+const PALETTE_SIZE: usize = 256;
+
+/// A trained color palette for one image: every learned swatch, and the
+/// single most prominent one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Palette {
+    pub colors: Vec<[u8; 3]>,
+    pub dominant: [u8; 3],
+}
+
+/// Trains a NeuQuant network on `image`'s pixels and buckets the learned
+/// palette by frequency of nearest-pixel assignment to find the single most
+/// prominent color.
+///
+/// Fully transparent pixels are excluded from the frequency count so a photo
+/// with transparent padding doesn't report that padding as dominant; if every
+/// pixel is transparent, all of them are counted instead so the result still
+/// reflects the (black) content rather than an arbitrary palette entry.
+pub fn extract(image: &DynamicImage) -> Palette {
+    let rgba = image.to_rgba8();
+    let pixels = rgba.as_raw();
+
+    let quant = NeuQuant::new(SAMPLE_FACTOR, PALETTE_SIZE, pixels);
+    let map = quant.color_map_rgb();
+    let colors: Vec<[u8; 3]> = map.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+
+    let mut counts = vec![0usize; colors.len()];
+    let mut opaque_pixels = pixels.chunks_exact(4).filter(|p| p[3] != 0).peekable();
+    let has_opaque = opaque_pixels.peek().is_some();
+
+    let tally = |chunk: &[u8]| {
+        let idx = quant.index_of(chunk) % counts.len().max(1);
+        if let Some(count) = counts.get_mut(idx) {
+            *count += 1;
+        }
+    };
+
+    if has_opaque {
+        opaque_pixels.for_each(tally);
+    } else {
+        pixels.chunks_exact(4).for_each(tally);
+    }
+
+    let dominant_idx = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, count)| **count)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+
+    let dominant = colors.get(dominant_idx).copied().unwrap_or([0, 0, 0]);
+
+    Palette { colors, dominant }
+}
+
+/// Serializes `palette` as the JSON body served at `/palette/{fname}.json`.
+pub fn to_json(palette: &Palette) -> Vec<u8> {
+    serde_json::to_vec(palette).expect("Serializing palette")
+}
@@ -0,0 +1,77 @@
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+/// A single byte range parsed from a `Range: bytes=...` request header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ByteRange {
+    start: usize,
+    end: usize,
+}
+
+/// Parses the first `bytes=start-end` range against a body of `total` bytes.
+///
+/// Supports open-ended ranges (`bytes=500-`) and suffix ranges (`bytes=-500`).
+/// Multi-range requests are not split; only the first range is honored.
+fn parse_range(header: &HeaderValue, total: usize) -> Option<ByteRange> {
+    let value = header.to_str().ok()?;
+    let spec = value.strip_prefix("bytes=")?;
+    let spec = spec.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+
+    if start.is_empty() {
+        let suffix_len: usize = end.parse().ok()?;
+        let start = total.saturating_sub(suffix_len);
+        Some(ByteRange {
+            start,
+            end: total.saturating_sub(1),
+        })
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = if end.is_empty() {
+            total.saturating_sub(1)
+        } else {
+            end.parse().ok()?
+        };
+        Some(ByteRange { start, end })
+    }
+}
+
+/// Serves an owned byte buffer, honoring an optional `Range` request header.
+///
+/// Always advertises `Accept-Ranges: bytes`. Returns `206 Partial Content` with
+/// a `Content-Range` header when a satisfiable range is requested, or
+/// `416 Range Not Satisfiable` with `Content-Range: bytes */total` when the
+/// requested start is beyond the body length.
+pub fn ranged_response(body: Vec<u8>, content_type: &'static str, headers: &HeaderMap) -> Response {
+    let total = body.len();
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|h| parse_range(h, total));
+
+    let mut resp = match range {
+        Some(range) if total == 0 || range.start >= total || range.start > range.end => {
+            let mut resp = StatusCode::RANGE_NOT_SATISFIABLE.into_response();
+            resp.headers_mut().insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{total}")).unwrap(),
+            );
+            return resp;
+        }
+        Some(range) => {
+            let end = range.end.min(total - 1);
+            let slice = body[range.start..=end].to_vec();
+            let mut resp = (StatusCode::PARTIAL_CONTENT, slice).into_response();
+            resp.headers_mut().insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {}-{end}/{total}", range.start)).unwrap(),
+            );
+            resp
+        }
+        None => body.into_response(),
+    };
+
+    let headers_mut = resp.headers_mut();
+    headers_mut.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers_mut.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    resp
+}
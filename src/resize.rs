@@ -0,0 +1,117 @@
+//! On-demand resizing: derives arbitrary preview sizes from a cached full
+//! image instead of requiring every variant to be pre-generated at startup.
+
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use image::imageops::FilterType;
+use serde::Deserialize;
+
+use crate::cache::ImageCache;
+use crate::gallery::Gallery;
+
+/// Query parameters accepted by `/resize/{fname}`.
+#[derive(Debug, Deserialize)]
+pub struct ResizeParams {
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub q: Option<f32>,
+    pub fmt: Option<String>,
+}
+
+/// Serves `fname` scaled to `params`'s bounds (preserving aspect ratio when
+/// only one dimension is given), encoded at the requested quality/format.
+/// Results are cached on `(fname, w, h, q, fmt)` so repeat requests are
+/// served straight from the cache.
+pub async fn serve(
+    gallery: &Gallery,
+    cache: &ImageCache,
+    fname: &str,
+    params: ResizeParams,
+    headers: &HeaderMap,
+) -> Response {
+    if !gallery.contains(fname) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let fmt = params.fmt.as_deref().unwrap_or("webp").to_string();
+    let quality = params.q.unwrap_or(80.0);
+    let key = format!(
+        "resize_{fname}_{}x{}_q{quality}.{fmt}",
+        params
+            .w
+            .map(|w| w.to_string())
+            .unwrap_or_else(|| "auto".into()),
+        params
+            .h
+            .map(|h| h.to_string())
+            .unwrap_or_else(|| "auto".into()),
+    );
+
+    if !cache.contains(&key) {
+        let Some((_, full)) = cache.get(fname).ok().flatten() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        let w = params.w;
+        let h = params.h;
+        let encoded =
+            tokio::task::spawn_blocking(move || encode_resized(&full, w, h, quality, &fmt))
+                .await
+                .expect("Resize task panicked");
+
+        match encoded {
+            Ok((bytes, content_type)) => {
+                if let Err(e) = cache.insert(&key, content_type, &bytes) {
+                    log::error!(target: "resize", "Failed to cache resized image {key}: {e:?}");
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            }
+            Err(e) => {
+                log::error!(target: "resize", "Failed to resize {fname}: {e}");
+                return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+            }
+        }
+    }
+
+    cache.respond(&key, headers)
+}
+
+/// Returns the encoded bytes and content type for `full` resized to `(w, h)`.
+fn encode_resized(
+    full: &[u8],
+    w: Option<u32>,
+    h: Option<u32>,
+    quality: f32,
+    fmt: &str,
+) -> Result<(Vec<u8>, &'static str), String> {
+    let image = image::load_from_memory(full).map_err(|e| e.to_string())?;
+
+    let (orig_w, orig_h) = (image.width(), image.height());
+    let (target_w, target_h) = match (w, h) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, (orig_h as f64 * w as f64 / orig_w as f64).round() as u32),
+        (None, Some(h)) => ((orig_w as f64 * h as f64 / orig_h as f64).round() as u32, h),
+        (None, None) => (orig_w, orig_h),
+    };
+
+    let resized = image.resize(target_w.max(1), target_h.max(1), FilterType::Lanczos3);
+
+    match fmt {
+        "jpeg" | "jpg" => {
+            let mut bytes = Vec::new();
+            resized
+                .write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                    &mut bytes,
+                    quality.clamp(1.0, 100.0) as u8,
+                ))
+                .map_err(|e| e.to_string())?;
+            Ok((bytes, "image/jpeg"))
+        }
+        _ => {
+            let bytes = webp::Encoder::from_image(&resized)
+                .map_err(|e| e.to_string())?
+                .encode(quality.clamp(1.0, 100.0));
+            Ok((bytes.to_vec(), "image/webp"))
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! `POST /upload`: accepts one or more JPEG fields, runs them through the
+//! same decode -> WebP preview -> cache pipeline used at startup, and
+//! appends them to the shared [`Gallery`] so they show up without a restart.
+
+use std::sync::Arc;
+
+use axum::extract::Multipart;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use image::codecs::jpeg::JpegDecoder;
+use image::DynamicImage;
+use log::error;
+
+use crate::cache::ImageCache;
+use crate::gallery::{Gallery, GalleryEntry};
+use crate::palette;
+
+/// Handles `POST /upload`, processing every JPEG field in the multipart body
+/// in turn. Fields without a file name are skipped. Once every field has been
+/// processed, the photos are registered with the gallery in a single batch, so
+/// an N-photo upload rebuilds the zip archive once rather than N times. If any
+/// field fails to read or process, the request is rejected and none of its
+/// photos are registered.
+pub async fn upload(
+    gallery: Arc<Gallery>,
+    preview_width: u32,
+    preview_height: u32,
+    quality: f32,
+    mut multipart: Multipart,
+) -> Response {
+    let mut entries = Vec::new();
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                error!(target: "upload", "Malformed multipart request: {e:?}");
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+        };
+
+        let Some(fname) = field.file_name().map(ToString::to_string) else {
+            continue;
+        };
+
+        let full = match field.bytes().await {
+            Ok(bytes) => bytes.to_vec(),
+            Err(e) => {
+                error!(target: "upload", "Failed to read upload body for {fname}: {e:?}");
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+        };
+
+        let cache = gallery.cache();
+        let result = tokio::task::spawn_blocking(move || {
+            process_upload(&cache, &fname, full, preview_width, preview_height, quality)
+        })
+        .await
+        .expect("Upload processing task panicked");
+
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                error!(target: "upload", "Failed to process upload: {e}");
+                return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+            }
+        }
+    }
+
+    gallery.extend(entries);
+
+    StatusCode::CREATED.into_response()
+}
+
+/// Decodes `full`, generates its preview and palette, and caches the full
+/// image and the preview, returning the [`GalleryEntry`] for the caller to register with the
+/// gallery.
+fn process_upload(
+    cache: &ImageCache,
+    fname: &str,
+    full: Vec<u8>,
+    preview_width: u32,
+    preview_height: u32,
+    quality: f32,
+) -> Result<GalleryEntry, String> {
+    let stem = std::path::Path::new(fname)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(fname);
+    let preview_key = format!("preview_{stem}.webp");
+
+    let mut decoder = JpegDecoder::new(full.as_slice()).map_err(|e| e.to_string())?;
+    decoder
+        .scale(preview_width as u16, preview_height as u16)
+        .map_err(|e| e.to_string())?;
+    let image = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+
+    let palette = palette::extract(&image);
+
+    let preview = webp::Encoder::from_image(&image)
+        .map_err(|e| e.to_string())?
+        .encode(quality);
+
+    cache
+        .insert(fname, "image/jpeg", &full)
+        .map_err(|e| e.to_string())?;
+    cache
+        .insert(&preview_key, "image/webp", &preview)
+        .map_err(|e| e.to_string())?;
+
+    Ok(GalleryEntry {
+        fname: fname.to_string(),
+        preview_key,
+        palette,
+    })
+}